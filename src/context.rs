@@ -0,0 +1,53 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::types::MarkdownPart;
+
+/// Default ceiling on `{{ include(...) }}` nesting before `evaluate` gives up
+/// with `CompilationError::IncludeDepthExceeded` instead of overflowing the
+/// stack.
+pub const DEFAULT_MAX_INCLUDE_DEPTH: usize = 64;
+
+/// Macro bodies defined via `{{ def(...) }}`, shared by every `Context`
+/// cloned from the same compilation so `expand` can see definitions made
+/// anywhere in the document (including inside includes).
+pub type ScopeStore = Rc<RefCell<HashMap<String, Vec<MarkdownPart>>>>;
+
+/// Per-call evaluation state threaded through `evaluate` and handed to every
+/// `Plugin::function_called` invocation.
+#[derive(Debug, Clone)]
+pub struct Context {
+    pub path: PathBuf,
+    /// The file currently being evaluated. Unlike `path` (its directory,
+    /// used only to resolve relative includes), this always names an actual
+    /// file, so anything that needs to attribute content to "the file this
+    /// came from" — e.g. parsing a `{{ def(...) }}` macro body — should use
+    /// this instead of `path`.
+    pub current_file: PathBuf,
+    /// Canonicalized paths of the entry file and every file currently being
+    /// included, innermost last. Used by `IncludePlugin` to detect include cycles and bound
+    /// recursion depth.
+    pub include_chain: Vec<PathBuf>,
+    pub max_include_depth: usize,
+    pub scope: ScopeStore,
+}
+
+impl Context {
+    pub fn new(file: PathBuf) -> Self {
+        // Seed the chain with the entry file itself (canonicalized, like
+        // every other entry `IncludePlugin` pushes) so a direct cycle back to
+        // it is caught on its first return instead of needing a second full
+        // expansion, and so the reported chain starts at the file the user
+        // actually invoked rather than being rotated relative to it.
+        let canonical = file.canonicalize().unwrap_or_else(|_| file.clone());
+        Self {
+            path: file.parent().unwrap_or_else(|| Path::new(".")).into(),
+            current_file: file,
+            include_chain: vec![canonical],
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
+            scope: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+}
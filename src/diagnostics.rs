@@ -0,0 +1,78 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use crate::plugin::PluginError;
+use crate::CompilationError;
+
+/// A byte-offset range into a source file, used to point error messages at
+/// the exact text that caused them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: PathBuf,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// An error that can be pointed at a location in its source file.
+pub trait Diagnostic: Display {
+    fn span(&self) -> Option<&Span>;
+}
+
+impl Diagnostic for CompilationError {
+    fn span(&self) -> Option<&Span> {
+        CompilationError::span(self)
+    }
+}
+
+impl Diagnostic for PluginError {
+    fn span(&self) -> Option<&Span> {
+        PluginError::span(self)
+    }
+}
+
+/// Renders `error` as a compiler-style message: the error text followed by
+/// the offending source line with a caret underline beneath the span.
+///
+/// `source` must be the full contents of `error`'s span's file. Errors with
+/// no span (e.g. `CompilationError::IOError`) fall back to their plain
+/// `Display` output.
+pub fn render_diagnostic(error: &impl Diagnostic, source: &str) -> String {
+    let Some(span) = error.span() else {
+        return error.to_string();
+    };
+
+    let mut line_start = 0;
+    let mut line_no = 1;
+    for (i, ch) in source.char_indices() {
+        if i >= span.start {
+            break;
+        }
+        if ch == '\n' {
+            line_start = i + 1;
+            line_no += 1;
+        }
+    }
+    let line_end = source[line_start..]
+        .find('\n')
+        .map(|i| line_start + i)
+        .unwrap_or(source.len());
+    let line = &source[line_start..line_end];
+    // `span` is a byte range; `line` may contain multi-byte UTF-8 characters
+    // before or within it, so the caret padding/width must be counted in
+    // chars, not bytes, or it drifts right of the real token.
+    let byte_col = span.start.saturating_sub(line_start);
+    let byte_end = span.end.saturating_sub(line_start).min(line.len());
+    let col = line[..byte_col].chars().count();
+    let underline_len = line[byte_col..byte_end].chars().count().max(1);
+
+    format!(
+        "{}:{}:{}: {}\n{}\n{}{}",
+        span.file.display(),
+        line_no,
+        col + 1,
+        error,
+        line,
+        " ".repeat(col),
+        "^".repeat(underline_len),
+    )
+}
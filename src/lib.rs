@@ -0,0 +1,407 @@
+pub mod context;
+pub mod diagnostics;
+pub mod plugin;
+pub mod plugins;
+pub mod types;
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+pub use diagnostics::Span;
+pub use types::{Function, MarkdownPart, Type, Value};
+
+use plugin::{Plugin, PluginError};
+
+#[derive(Debug, Error)]
+pub enum CompilationError {
+    #[error("{0}")]
+    PluginError(PluginError),
+    #[error("{0}")]
+    IOError(std::io::Error),
+    #[error("{0}")]
+    CallParseError(CallParseError),
+    #[error("include cycle detected: {}", format_include_chain(.0))]
+    IncludeCycle(Vec<PathBuf>),
+    #[error("maximum include depth exceeded")]
+    IncludeDepthExceeded,
+}
+
+impl CompilationError {
+    /// The source span this error should be reported at, if any.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            CompilationError::CallParseError(err) => Some(err.span()),
+            CompilationError::PluginError(err) => err.span(),
+            CompilationError::IOError(_) => None,
+            CompilationError::IncludeCycle(_) => None,
+            CompilationError::IncludeDepthExceeded => None,
+        }
+    }
+}
+
+fn format_include_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|p| p.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+impl From<std::io::Error> for CompilationError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IOError(value)
+    }
+}
+
+impl From<CallParseError> for CompilationError {
+    fn from(value: CallParseError) -> Self {
+        CompilationError::CallParseError(value)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CallParseError {
+    #[error("unexpected symbol `{symbol}`")]
+    InvalidSymbol { symbol: char, span: Span },
+    #[error("call argument is empty")]
+    EmptyArgument { span: Span },
+    #[error("unclosed literal")]
+    UnclosedLiteral { span: Span },
+    #[error("invalid integer literal: {source}")]
+    ParseIntError {
+        source: std::num::ParseIntError,
+        span: Span,
+    },
+}
+
+impl CallParseError {
+    pub fn span(&self) -> &Span {
+        match self {
+            CallParseError::InvalidSymbol { span, .. }
+            | CallParseError::EmptyArgument { span }
+            | CallParseError::UnclosedLiteral { span }
+            | CallParseError::ParseIntError { span, .. } => span,
+        }
+    }
+}
+
+pub fn parse_md(path: &Path) -> Result<Vec<MarkdownPart>, CompilationError> {
+    let content = std::fs::read_to_string(path)?;
+    parse_md_str(&content, path)
+}
+
+/// Core of `parse_md`, operating on already-loaded content. `path` is
+/// attributed to every produced `MarkdownPart` and span, and is used to
+/// resolve `{{ include(...) }}` targets relative to it; it need not refer to
+/// an existing file.
+pub fn parse_md_str(content: &str, path: &Path) -> Result<Vec<MarkdownPart>, CompilationError> {
+    let mut parts = vec![];
+    enum CurrentState {
+        InText,
+        InCall,
+    }
+    let mut buf = String::new();
+    let mut cs = CurrentState::InText;
+    let mut call_start = 0usize;
+    // Quote state for the `}}` terminator scan below, tracked the same way
+    // `parse_call` tracks it, so a `}}` inside a quoted string/path argument
+    // (e.g. a macro body embedding `{{ include(...) }}` as literal text)
+    // doesn't end the call early.
+    let mut in_string = false;
+    let mut in_path = false;
+
+    for (i, c) in content.char_indices() {
+        match cs {
+            CurrentState::InText => {
+                if c == '{' && buf.ends_with('{') {
+                    buf.pop();
+                    parts.push(MarkdownPart::Text {
+                        content: buf,
+                        source: path.into(),
+                    });
+                    // `i` is the index of the second `{`; the call started one byte earlier.
+                    call_start = i - 1;
+                    cs = CurrentState::InCall;
+                    buf = String::new();
+                } else {
+                    buf.push(c);
+                }
+            }
+            CurrentState::InCall => {
+                if c == '"' && !in_path {
+                    in_string = !in_string;
+                }
+                if c == '#' && !in_string {
+                    in_path = !in_path;
+                }
+                if c == '}' && buf.ends_with('}') && !in_string && !in_path {
+                    buf.pop();
+                    let (function, arguments) = parse_call(&buf, path, call_start + 2)?;
+                    parts.push(MarkdownPart::Call {
+                        function,
+                        arguments,
+                        source: path.into(),
+                        span: Span {
+                            file: path.into(),
+                            start: call_start,
+                            end: i + 1,
+                        },
+                    });
+                    cs = CurrentState::InText;
+                    buf = String::new();
+                    in_string = false;
+                    in_path = false;
+                } else {
+                    buf.push(c)
+                }
+            }
+        }
+    }
+    parts.push(MarkdownPart::Text {
+        content: buf,
+        source: path.into(),
+    });
+    Ok(parts)
+}
+
+/// Parses `#path#`/`"string"`/number arguments. `start` is the absolute byte
+/// offset of `buf` within its source file, used to build accurate spans.
+fn parse_arg(buf: &str, file: &Path, start: usize) -> Result<Value, CallParseError> {
+    let leading_ws = buf.len() - buf.trim_start().len();
+    let trimmed = buf.trim();
+    let arg_start = start + leading_ws;
+    let whole_span = Span {
+        file: file.into(),
+        start: arg_start,
+        end: arg_start + trimmed.len(),
+    };
+    let open_span = Span {
+        file: file.into(),
+        start: arg_start,
+        end: arg_start + 1,
+    };
+
+    match trimmed.chars().next() {
+        None => Err(CallParseError::EmptyArgument { span: whole_span }),
+        Some('#') => {
+            if trimmed.len() < 2 || !trimmed.ends_with('#') {
+                Err(CallParseError::UnclosedLiteral { span: open_span })
+            } else {
+                Ok(Value::Path(trimmed[1..trimmed.len() - 1].into()))
+            }
+        }
+        Some('"') => {
+            if trimmed.len() < 2 || !trimmed.ends_with('"') {
+                Err(CallParseError::UnclosedLiteral { span: open_span })
+            } else {
+                Ok(Value::String(trimmed[1..trimmed.len() - 1].into()))
+            }
+        }
+        _ => trimmed
+            .parse()
+            .map(Value::Number)
+            .map_err(|source| CallParseError::ParseIntError {
+                source,
+                span: whole_span,
+            }),
+    }
+}
+
+/// Parses the inside of a `{{ ... }}` call. `base_offset` is the absolute
+/// byte offset of `buffer`'s first character within `file`, so that every
+/// error produced here carries a span pointing at the real source location.
+fn parse_call(
+    buffer: &str,
+    file: &Path,
+    base_offset: usize,
+) -> Result<(String, Vec<Value>), CallParseError> {
+    enum CurrentState {
+        Start,
+        FunctionName,
+        FunctionArgs,
+    }
+    let mut in_path = false;
+    let mut in_string = false;
+    let mut cs = CurrentState::Start;
+    let mut function_name = String::new();
+    let mut args: Vec<Value> = vec![];
+    let mut buf = String::new();
+    let mut arg_start = base_offset;
+
+    for (i, c) in buffer.char_indices() {
+        match cs {
+            CurrentState::Start => {
+                if !c.is_whitespace() {
+                    cs = CurrentState::FunctionName;
+                    buf.push(c);
+                }
+            }
+            CurrentState::FunctionName => {
+                if c.is_alphanumeric() {
+                    buf.push(c);
+                } else if c.is_whitespace() {
+                } else if c == '(' {
+                    function_name = std::mem::take(&mut buf);
+                    arg_start = base_offset + i + c.len_utf8();
+                    cs = CurrentState::FunctionArgs;
+                } else {
+                    return Err(CallParseError::InvalidSymbol {
+                        symbol: c,
+                        span: Span {
+                            file: file.into(),
+                            start: base_offset + i,
+                            end: base_offset + i + c.len_utf8(),
+                        },
+                    });
+                }
+            }
+            CurrentState::FunctionArgs => {
+                if c == '"' && !in_path {
+                    in_string = !in_string;
+                }
+                if c == '#' && !in_string {
+                    in_path = !in_path;
+                }
+                if in_path || in_string {
+                    buf.push(c)
+                } else if c == ')' {
+                    args.push(parse_arg(&buf, file, arg_start)?);
+                    buf.clear();
+                    break;
+                } else if c == ',' {
+                    args.push(parse_arg(&buf, file, arg_start)?);
+                    buf.clear();
+                    arg_start = base_offset + i + c.len_utf8();
+                } else {
+                    buf.push(c);
+                }
+            }
+        }
+    }
+    Ok((function_name, args))
+}
+
+/// Whether `arguments` matches some signature in `signatures`: same arity,
+/// and each value's `Type` equal to the corresponding expected `Type`.
+fn signature_matches(arguments: &[Value], signatures: &[&'static [Type]]) -> bool {
+    signatures.iter().any(|signature| {
+        signature.len() == arguments.len()
+            && signature
+                .iter()
+                .zip(arguments.iter())
+                .all(|(expected, got)| *expected == got.ty())
+    })
+}
+
+pub fn evaluate(
+    content: Vec<MarkdownPart>,
+    plugins: &[Box<dyn Plugin>],
+    ctx: &context::Context,
+) -> Result<Vec<MarkdownPart>, PluginError> {
+    let mut new_parts = vec![];
+    for part in content.into_iter() {
+        match part {
+            MarkdownPart::Text { .. } => new_parts.push(part),
+            MarkdownPart::Call {
+                function,
+                arguments,
+                source,
+                span,
+            } => {
+                let call_ctx = context::Context {
+                    path: source.parent().unwrap().into(),
+                    current_file: source.clone(),
+                    include_chain: ctx.include_chain.clone(),
+                    max_include_depth: ctx.max_include_depth,
+                    scope: ctx.scope.clone(),
+                };
+                for pl in plugins.iter() {
+                    let Some(signature) = pl.exposed_functions().iter().find(|f| f.name == function)
+                    else {
+                        continue;
+                    };
+                    if !signature_matches(&arguments, signature.signatures) {
+                        return Err(PluginError::SignatureMismatch {
+                            function,
+                            got: arguments.iter().map(Value::ty).collect(),
+                            expected: signature.signatures.iter().map(|s| s.to_vec()).collect(),
+                            span,
+                        });
+                    }
+                    let resolved = pl.function_called(&function, arguments, call_ctx.clone(), plugins)?;
+                    let parts = evaluate(resolved, plugins, &call_ctx)?;
+                    new_parts.extend(parts.into_iter());
+                    break;
+                }
+            }
+        }
+    }
+    Ok(new_parts)
+}
+
+pub fn rebuild(content: Vec<MarkdownPart>) -> String {
+    let mut new_content: String = String::new();
+    for part in content {
+        match part {
+            MarkdownPart::Text { content, .. } => {
+                new_content.push_str(&content);
+            }
+            MarkdownPart::Call { .. } => {
+                panic!("Call in rebuild")
+            }
+        }
+    }
+    new_content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::macros::MacroPlugin;
+
+    #[test]
+    fn macro_def_nested_call_does_not_panic_when_entry_file_has_no_parent_dir() {
+        // Regression test: `def`'s body used to be parsed with the calling
+        // context's directory standing in for a file, so a macro body
+        // containing a call (here `expand`) panicked on `source.parent()`
+        // once that directory was itself empty (i.e. the entry file has no
+        // parent, as below).
+        let plugins: Vec<Box<dyn Plugin>> = vec![Box::new(MacroPlugin::new())];
+        let ctx = context::Context::new(PathBuf::from("doc.md"));
+        let content = parse_md_str(
+            r#"{{ def("inner", "X") }}{{ def("outer", "before {{ expand("inner") }} after") }}{{ expand("outer") }}"#,
+            Path::new("doc.md"),
+        )
+        .unwrap();
+
+        let result = evaluate(content, &plugins, &ctx).unwrap();
+
+        assert_eq!(rebuild(result), "before X after");
+    }
+
+    #[test]
+    fn macro_body_embedding_a_call_is_not_truncated_at_its_inner_braces() {
+        let content = r#"{{ def("outer", "before {{ include(#k.md#) }} after") }}"#;
+        let parts = parse_md_str(content, Path::new("doc.md")).unwrap();
+
+        assert_eq!(parts.len(), 3);
+        match &parts[1] {
+            MarkdownPart::Call {
+                function,
+                arguments,
+                ..
+            } => {
+                assert_eq!(function, "def");
+                assert_eq!(
+                    arguments,
+                    &vec![
+                        Value::String("outer".into()),
+                        Value::String("before {{ include(#k.md#) }} after".into()),
+                    ]
+                );
+            }
+            other => panic!("expected a Call part, got {other:?}"),
+        }
+    }
+}
@@ -1,11 +1,19 @@
 use thiserror::Error;
-use crate::{CompilationError, MarkdownPart};
+use crate::{CompilationError, MarkdownPart, Span};
 use crate::context::Context;
-use crate::types::{Function, Value};
+use crate::types::{Function, Type, Value};
 
 pub trait Plugin {
     fn exposed_functions(&self) -> &'static [Function];
-    fn function_called(&self, function: &str, arguments: Vec<Value>, ctx: Context) -> Result<Vec<MarkdownPart>, PluginError>;
+    /// `plugins` is the full active plugin set, handed back to plugins (like
+    /// `IncludePlugin`) that need to recursively evaluate content of their own.
+    fn function_called(
+        &self,
+        function: &str,
+        arguments: Vec<Value>,
+        ctx: Context,
+        plugins: &[Box<dyn Plugin>],
+    ) -> Result<Vec<MarkdownPart>, PluginError>;
 }
 #[derive(Debug, Error)]
 pub enum PluginError {
@@ -17,5 +25,25 @@ pub enum PluginError {
     ExternalError(String),
     #[error("Nested compilation error: `{0}`")]
     CompilationError(Box<CompilationError>),
+    #[error("function `{function}` called with ({got:?}), expected one of {expected:?}")]
+    SignatureMismatch {
+        function: String,
+        got: Vec<Type>,
+        expected: Vec<Vec<Type>>,
+        span: Span,
+    },
+    #[error("macro `{0}` is not defined")]
+    UndefinedMacro(String),
+}
+
+impl PluginError {
+    /// The source span this error should be reported at, if any.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            PluginError::CompilationError(err) => err.span(),
+            PluginError::SignatureMismatch { span, .. } => Some(span),
+            _ => None,
+        }
+    }
 }
 
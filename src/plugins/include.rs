@@ -1,5 +1,7 @@
+use std::path::Path;
+
 use crate::context::Context;
-use crate::{MarkdownPart, parse_md};
+use crate::{evaluate, parse_md, CompilationError, MarkdownPart};
 use crate::plugin::{ Plugin, PluginError };
 use crate::types::*;
 
@@ -21,22 +23,48 @@ impl Plugin for IncludePlugin {
         ]
     }
 
-    fn function_called(&self, function: &str, arguments: Vec<Value>, ctx: Context) -> Result<Vec<MarkdownPart>, PluginError> {
+    fn function_called(
+        &self,
+        function: &str,
+        arguments: Vec<Value>,
+        ctx: Context,
+        plugins: &[Box<dyn Plugin>],
+    ) -> Result<Vec<MarkdownPart>, PluginError> {
         match function {
             "include" => {
-                match arguments.first() {
-                    None => { Err(PluginError::InvalidArguments) }
-                    Some(x) => {
-                        match x {
-                            Value::Path(path) => {
-                                let path = ctx.path.join(path);
-                                parse_md(&path)
-                                    .map_err(|err| PluginError::CompilationError(Box::new(err)))
-                            }
-                            _ => Err(PluginError::InvalidArguments)
-                        }
-                    }
+                // `evaluate` already checked `arguments` against `exposed_functions`.
+                let Value::Path(path) = &arguments[0] else {
+                    unreachable!("signature already validated by evaluate")
+                };
+                let resolved = ctx.path.join(path);
+                let canonical = resolved.canonicalize().unwrap_or_else(|_| resolved.clone());
+
+                if ctx.include_chain.contains(&canonical) {
+                    let mut chain = ctx.include_chain.clone();
+                    chain.push(canonical);
+                    return Err(PluginError::CompilationError(Box::new(
+                        CompilationError::IncludeCycle(chain),
+                    )));
+                }
+                if ctx.include_chain.len() >= ctx.max_include_depth {
+                    return Err(PluginError::CompilationError(Box::new(
+                        CompilationError::IncludeDepthExceeded,
+                    )));
                 }
+
+                let mut include_chain = ctx.include_chain.clone();
+                include_chain.push(canonical);
+                let child_ctx = Context {
+                    path: resolved.parent().unwrap_or_else(|| Path::new(".")).into(),
+                    current_file: resolved.clone(),
+                    include_chain,
+                    max_include_depth: ctx.max_include_depth,
+                    scope: ctx.scope.clone(),
+                };
+
+                let parts = parse_md(&resolved)
+                    .map_err(|err| PluginError::CompilationError(Box::new(err)))?;
+                evaluate(parts, plugins, &child_ctx)
             }
             _ => Err(PluginError::FunctionNotFound(function.into()))
         }
@@ -0,0 +1,64 @@
+use crate::context::Context;
+use crate::{parse_md_str, MarkdownPart};
+use crate::plugin::{Plugin, PluginError};
+use crate::types::*;
+
+/// Lets documents define their own reusable snippets: `{{ def("name", "body") }}`
+/// stores `body` (itself parsed, so it may contain further calls), and
+/// `{{ expand("name") }}` splices the stored parts back in. `def` must run
+/// before the matching `expand`; re-`def`ing a name overwrites it.
+pub struct MacroPlugin;
+
+impl MacroPlugin {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Plugin for MacroPlugin {
+    fn exposed_functions(&self) -> &'static [Function] {
+        &[
+            Function {
+                name: "def",
+                signatures: &[&[Type::String, Type::String]],
+            },
+            Function {
+                name: "expand",
+                signatures: &[&[Type::String]],
+            },
+        ]
+    }
+
+    fn function_called(
+        &self,
+        function: &str,
+        arguments: Vec<Value>,
+        ctx: Context,
+        _plugins: &[Box<dyn Plugin>],
+    ) -> Result<Vec<MarkdownPart>, PluginError> {
+        match function {
+            "def" => {
+                // `evaluate` already checked `arguments` against `exposed_functions`.
+                let (Value::String(name), Value::String(body)) = (&arguments[0], &arguments[1])
+                else {
+                    unreachable!("signature already validated by evaluate")
+                };
+                let parts = parse_md_str(body, &ctx.current_file)
+                    .map_err(|err| PluginError::CompilationError(Box::new(err)))?;
+                ctx.scope.borrow_mut().insert(name.clone(), parts);
+                Ok(vec![])
+            }
+            "expand" => {
+                let Value::String(name) = &arguments[0] else {
+                    unreachable!("signature already validated by evaluate")
+                };
+                ctx.scope
+                    .borrow()
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| PluginError::UndefinedMacro(name.clone()))
+            }
+            _ => Err(PluginError::FunctionNotFound(function.into())),
+        }
+    }
+}
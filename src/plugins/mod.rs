@@ -0,0 +1,2 @@
+pub mod include;
+pub mod macros;
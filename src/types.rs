@@ -1,5 +1,7 @@
 use std::path::PathBuf;
 
+use crate::diagnostics::Span;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     String,
@@ -14,6 +16,17 @@ pub enum Value {
     Number(i64)
 }
 
+impl Value {
+    /// The `Type` this value satisfies in a `Function` signature.
+    pub fn ty(&self) -> Type {
+        match self {
+            Value::String(_) => Type::String,
+            Value::Path(_) => Type::Path,
+            Value::Number(_) => Type::Number,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Function {
     pub name: &'static str,
@@ -30,5 +43,6 @@ pub enum MarkdownPart {
         function: String,
         arguments: Vec<Value>,
         source: PathBuf,
+        span: Span,
     },
 }